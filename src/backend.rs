@@ -0,0 +1,474 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use clap::ValueEnum;
+
+use crate::error::OthError;
+use crate::git2_backend::Git2Backend;
+
+const REMOTE_FALLBACK: &str = "origin";
+
+/// Which implementation resolves remote/branch/staged metadata for `GitBackend`.
+#[derive(Debug, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Engine {
+    /// Shell out to the `git` binary (the historical behavior).
+    Shell,
+    /// Use the in-process libgit2 backend.
+    Git2,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, ValueEnum)]
+pub enum DiffMode {
+    Branch,
+    Remote,
+    Revlist,
+    RevlistRemote,
+    Upstream,
+    /// Diff an arbitrary commit range given via `--range`, e.g. `v1.2.0..HEAD`.
+    Range,
+}
+
+/// Abstracts the VCS-specific logic so the file-selection-and-edit workflow
+/// in `main` works unchanged regardless of whether the working copy is
+/// backed by git, jj, or hg.
+pub trait Backend {
+    fn default_branch(&self) -> Result<String, OthError>;
+    fn upstream_remote(&self) -> Result<String, OthError>;
+    fn is_staged(&self) -> Result<bool, OthError>;
+    /// Builds the backend's equivalent revset/range for a `DiffMode`.
+    fn diff_spec(&self, diff_mode: &DiffMode) -> Result<String, OthError>;
+    fn changed_files(&self, diff_spec: &str, staged: bool) -> Result<Vec<String>, OthError>;
+    /// Preview command template with a `{}` placeholder for the path, used
+    /// by the `Finder` implementations.
+    fn preview_command(&self, diff_spec: &str, staged: bool) -> String;
+    /// Unified diff text for a single file, used to locate the first
+    /// changed hunk's line number for `--line-jump`.
+    fn file_diff(&self, diff_spec: &str, staged: bool, file: &str) -> Result<String, OthError>;
+}
+
+fn shell_output(program: &str, args: &[&str]) -> Result<String, OthError> {
+    let cmd = format!("{} {}", program, args.join(" "));
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| OthError::Message(format!("error running `{}`: {}", cmd, e)))?;
+    if !output.status.success() {
+        return Err(OthError::Message(String::from_utf8_lossy(&output.stderr).to_string()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The default backend, wrapping the shell/libgit2 git implementations.
+pub struct GitBackend {
+    workdir: String,
+    remote: String,
+    range: Option<String>,
+    git2_backend: Option<Git2Backend>,
+}
+
+impl GitBackend {
+    pub fn new(
+        cwd: &Path,
+        remote_override: Option<String>,
+        configured_remote: Option<String>,
+        engine: Engine,
+        range: Option<String>,
+    ) -> Result<Self, OthError> {
+        let git2_backend = match engine {
+            Engine::Git2 => Some(Git2Backend::open(cwd)?),
+            Engine::Shell => None,
+        };
+
+        // Prefer the already-open libgit2 handle for toplevel discovery so
+        // `--engine git2` doesn't shell out to `git rev-parse --show-toplevel`.
+        let workdir = match &git2_backend {
+            Some(backend) => backend.workdir()?,
+            None => shell_output("git", &["rev-parse", "--show-toplevel"])?,
+        };
+
+        let remote = if let Some(remote) = remote_override.filter(|r| !r.is_empty()) {
+            remote
+        } else if let Some(remote) = configured_remote.filter(|r| !r.is_empty()) {
+            remote
+        } else if let Some(backend) = &git2_backend {
+            backend.upstream_remote().unwrap_or_else(|_| Self::detect_remote())
+        } else {
+            Self::detect_remote()
+        };
+
+        Ok(Self {
+            workdir,
+            remote,
+            range,
+            git2_backend,
+        })
+    }
+
+    /// Repository toplevel directory resolved during construction.
+    pub fn workdir(&self) -> &str {
+        &self.workdir
+    }
+
+    /// Confirms `range` (e.g. `v1.2.0..HEAD`) resolves to real commits
+    /// before it's handed to `git diff`.
+    fn validate_range(&self, range: &str) -> Result<(), OthError> {
+        if let Some(backend) = &self.git2_backend {
+            return backend.resolve_range(range);
+        }
+        shell_output("git", &["rev-parse", range]).map(|_| ())
+    }
+
+    fn detect_remote() -> String {
+        shell_output(
+            "git",
+            &["rev-parse", "--abbrev-ref", "--symbolic-full-name", "@{u}"],
+        )
+        .ok()
+        .and_then(|full_name| full_name.split('/').next().map(String::from))
+        .unwrap_or_else(|| REMOTE_FALLBACK.to_string())
+    }
+}
+
+impl Backend for GitBackend {
+    fn upstream_remote(&self) -> Result<String, OthError> {
+        Ok(self.remote.clone())
+    }
+
+    fn default_branch(&self) -> Result<String, OthError> {
+        if let Some(backend) = &self.git2_backend {
+            return backend.default_branch(&self.remote);
+        }
+
+        let remote_head = Path::new(&self.workdir).join(format!(".git/refs/remotes/{}/HEAD", self.remote));
+        let content = std::fs::read_to_string(&remote_head)
+            .map_err(|_| OthError::Message(format!("could not read remote HEAD {}", remote_head.display())))?;
+        content
+            .trim()
+            .strip_prefix(&format!("ref: refs/remotes/{}/", self.remote))
+            .map(String::from)
+            .ok_or_else(|| OthError::Message(format!("unexpected remote HEAD contents: {}", content)))
+    }
+
+    fn is_staged(&self) -> Result<bool, OthError> {
+        if let Some(backend) = &self.git2_backend {
+            return backend.is_staged();
+        }
+        let output = shell_output("git", &["diff", "--cached", "--shortstat"])?;
+        Ok(!output.is_empty())
+    }
+
+    fn diff_spec(&self, diff_mode: &DiffMode) -> Result<String, OthError> {
+        if let DiffMode::Range = diff_mode {
+            let range = self
+                .range
+                .clone()
+                .ok_or_else(|| OthError::Message("range mode requires --range <A..B>".to_string()))?;
+            self.validate_range(&range)?;
+            return Ok(range);
+        }
+
+        let default_branch = self.default_branch()?;
+        let spec = match diff_mode {
+            DiffMode::Branch => default_branch,
+            DiffMode::Upstream => {
+                let branch = match &self.git2_backend {
+                    Some(backend) => backend.current_branch()?,
+                    None => shell_output("git", &["branch", "--show-current"])?,
+                };
+                format!("{}/{}", self.remote, branch)
+            }
+            DiffMode::Remote => format!("{}/{}", self.remote, default_branch),
+            DiffMode::Revlist => {
+                let count = self.rev_list_count(&default_branch)?;
+                format!("HEAD~{}", count)
+            }
+            DiffMode::RevlistRemote => {
+                let count = self.rev_list_count(&default_branch)?;
+                format!("{}/HEAD~{}", self.remote, count)
+            }
+            DiffMode::Range => unreachable!("handled above"),
+        };
+        Ok(spec)
+    }
+
+    fn changed_files(&self, diff_spec: &str, staged: bool) -> Result<Vec<String>, OthError> {
+        if let Some(backend) = &self.git2_backend {
+            return backend.changed_files(diff_spec, staged);
+        }
+
+        let mut args = vec!["diff", diff_spec, "--name-only"];
+        if staged {
+            args.push("--cached");
+        }
+        let output = shell_output("git", &args)?;
+        Ok(output
+            .split('\n')
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect())
+    }
+
+    fn preview_command(&self, diff_spec: &str, staged: bool) -> String {
+        let cached = if staged { " --cached" } else { "" };
+        format!("git diff {}{} --color=always -- {{}}", diff_spec, cached)
+    }
+
+    fn file_diff(&self, diff_spec: &str, staged: bool, file: &str) -> Result<String, OthError> {
+        if let Some(backend) = &self.git2_backend {
+            return backend.file_diff(diff_spec, staged, file);
+        }
+        let mut args = vec!["diff", diff_spec];
+        if staged {
+            args.push("--cached");
+        }
+        args.push("--");
+        args.push(file);
+        shell_output("git", &args)
+    }
+}
+
+impl GitBackend {
+    fn rev_list_count(&self, default_branch: &str) -> Result<String, OthError> {
+        if let Some(backend) = &self.git2_backend {
+            let (ahead, _behind) = backend.ahead_behind(default_branch)?;
+            return Ok(ahead.to_string());
+        }
+        shell_output(
+            "git",
+            &["rev-list", "--count", "HEAD", &format!("^{}", default_branch)],
+        )
+    }
+}
+
+/// The kind of working copy found by `detect_vcs`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VcsKind {
+    Git,
+    Jujutsu,
+    Mercurial,
+}
+
+/// Detects whether `start` (or an ancestor) is a `jj` or `hg` working copy
+/// before falling back to `GitBackend`.
+pub fn detect_vcs(start: &Path) -> VcsKind {
+    let mut dir = Some(start);
+    while let Some(current) = dir {
+        if current.join(".jj").is_dir() {
+            return VcsKind::Jujutsu;
+        }
+        if current.join(".hg").is_dir() {
+            return VcsKind::Mercurial;
+        }
+        if current.join(".git").exists() {
+            return VcsKind::Git;
+        }
+        dir = current.parent();
+    }
+    VcsKind::Git
+}
+
+/// `jj` working copies are colocated with git under the hood for most repos
+/// oth targets, but expose their own revset syntax instead of git's.
+pub struct JujutsuBackend {
+    workdir: PathBuf,
+    range: Option<String>,
+}
+
+impl JujutsuBackend {
+    pub fn new(workdir: PathBuf, range: Option<String>) -> Self {
+        Self { workdir, range }
+    }
+
+    fn jj(&self, args: &[&str]) -> Result<String, OthError> {
+        let output = Command::new("jj")
+            .current_dir(&self.workdir)
+            .args(args)
+            .output()
+            .map_err(|e| OthError::Message(format!("error running jj: {}", e)))?;
+        if !output.status.success() {
+            return Err(OthError::Message(String::from_utf8_lossy(&output.stderr).to_string()));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
+
+impl Backend for JujutsuBackend {
+    fn default_branch(&self) -> Result<String, OthError> {
+        self.jj(&["log", "-r", "trunk()", "--no-graph", "-T", "bookmarks"])
+    }
+
+    fn upstream_remote(&self) -> Result<String, OthError> {
+        let remotes = self.jj(&["git", "remote", "list"])?;
+        remotes
+            .lines()
+            .next()
+            .and_then(|line| line.split(' ').next())
+            .map(String::from)
+            .ok_or_else(|| OthError::Message("no jj git remote configured".to_string()))
+    }
+
+    // jj has no staging area: every working-copy change is already part of
+    // the current change.
+    fn is_staged(&self) -> Result<bool, OthError> {
+        Ok(false)
+    }
+
+    fn diff_spec(&self, diff_mode: &DiffMode) -> Result<String, OthError> {
+        if let DiffMode::Range = diff_mode {
+            return self
+                .range
+                .clone()
+                .ok_or_else(|| OthError::Message("range mode requires --range <A..B>".to_string()));
+        }
+
+        let trunk = self.default_branch()?;
+        let spec = match diff_mode {
+            DiffMode::Branch | DiffMode::Remote | DiffMode::Upstream => format!("{}..@", trunk),
+            DiffMode::Revlist | DiffMode::RevlistRemote => format!("{}..@", trunk),
+            DiffMode::Range => unreachable!("handled above"),
+        };
+        Ok(spec)
+    }
+
+    fn changed_files(&self, diff_spec: &str, _staged: bool) -> Result<Vec<String>, OthError> {
+        let output = self.jj(&["diff", "-r", diff_spec, "--name-only"])?;
+        Ok(output
+            .lines()
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect())
+    }
+
+    fn preview_command(&self, diff_spec: &str, _staged: bool) -> String {
+        format!("jj diff -r {} --color=always -- {{}}", diff_spec)
+    }
+
+    fn file_diff(&self, diff_spec: &str, _staged: bool, file: &str) -> Result<String, OthError> {
+        self.jj(&["diff", "-r", diff_spec, "--git", "--", file])
+    }
+}
+
+/// Mercurial has no concept of a remote-tracking "default branch" ref like
+/// git; `default` is the conventional main branch name.
+pub struct HgBackend {
+    workdir: PathBuf,
+    range: Option<String>,
+}
+
+impl HgBackend {
+    pub fn new(workdir: PathBuf, range: Option<String>) -> Self {
+        Self { workdir, range }
+    }
+
+    fn hg(&self, args: &[&str]) -> Result<String, OthError> {
+        let output = Command::new("hg")
+            .current_dir(&self.workdir)
+            .args(args)
+            .output()
+            .map_err(|e| OthError::Message(format!("error running hg: {}", e)))?;
+        if !output.status.success() {
+            return Err(OthError::Message(String::from_utf8_lossy(&output.stderr).to_string()));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
+
+impl Backend for HgBackend {
+    fn default_branch(&self) -> Result<String, OthError> {
+        Ok("default".to_string())
+    }
+
+    fn upstream_remote(&self) -> Result<String, OthError> {
+        Ok("default".to_string())
+    }
+
+    // hg has no staging area either; `hg commit` takes the full working
+    // copy diff unless paths are given explicitly.
+    fn is_staged(&self) -> Result<bool, OthError> {
+        Ok(false)
+    }
+
+    fn diff_spec(&self, diff_mode: &DiffMode) -> Result<String, OthError> {
+        if let DiffMode::Range = diff_mode {
+            return self
+                .range
+                .clone()
+                .ok_or_else(|| OthError::Message("range mode requires --range <A..B>".to_string()));
+        }
+        self.default_branch()
+    }
+
+    fn changed_files(&self, diff_spec: &str, _staged: bool) -> Result<Vec<String>, OthError> {
+        let output = self.hg(&["status", "--rev", diff_spec, "--no-status"])?;
+        Ok(output
+            .lines()
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect())
+    }
+
+    fn preview_command(&self, diff_spec: &str, _staged: bool) -> String {
+        format!("hg diff --rev {} --color=always -- {{}}", diff_spec)
+    }
+
+    fn file_diff(&self, diff_spec: &str, _staged: bool, file: &str) -> Result<String, OthError> {
+        self.hg(&["diff", "--rev", diff_spec, "--", file])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backend, DiffMode, GitBackend, HgBackend, JujutsuBackend};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_git_diff_spec_range() {
+        let backend = GitBackend {
+            workdir: "/tmp".to_string(),
+            remote: "origin".to_string(),
+            // `diff_spec` validates the range with `git rev-parse`, so it
+            // must name revisions that exist in this repository.
+            range: Some("HEAD..HEAD".to_string()),
+            git2_backend: None,
+        };
+        assert_eq!(backend.diff_spec(&DiffMode::Range).unwrap(), "HEAD..HEAD");
+    }
+
+    #[test]
+    fn test_git_diff_spec_range_requires_range_arg() {
+        let backend = GitBackend {
+            workdir: "/tmp".to_string(),
+            remote: "origin".to_string(),
+            range: None,
+            git2_backend: None,
+        };
+        assert!(backend.diff_spec(&DiffMode::Range).is_err());
+    }
+
+    #[test]
+    fn test_jujutsu_diff_spec_range() {
+        let backend = JujutsuBackend {
+            workdir: PathBuf::from("/tmp"),
+            range: Some("a..b".to_string()),
+        };
+        assert_eq!(backend.diff_spec(&DiffMode::Range).unwrap(), "a..b");
+    }
+
+    #[test]
+    fn test_hg_diff_spec_range() {
+        let backend = HgBackend {
+            workdir: PathBuf::from("/tmp"),
+            range: Some("1::2".to_string()),
+        };
+        assert_eq!(backend.diff_spec(&DiffMode::Range).unwrap(), "1::2");
+    }
+
+    #[test]
+    fn test_hg_diff_spec_defaults_to_default_branch() {
+        let backend = HgBackend {
+            workdir: PathBuf::from("/tmp"),
+            range: None,
+        };
+        assert_eq!(backend.diff_spec(&DiffMode::Branch).unwrap(), "default");
+    }
+}
@@ -0,0 +1,83 @@
+use std::process::Command;
+
+/// Typed reads of `git config`, so users can persist `oth` preferences
+/// per-repo or globally instead of passing the equivalent flag every time.
+pub struct GitConfig;
+
+impl GitConfig {
+    pub fn get_string(key: &str, default: &str) -> String {
+        // `git config --type` has no "string" value; plain strings are the
+        // default and passing one explicitly makes the command exit 128.
+        Self::get(key, default, None)
+    }
+
+    pub fn get_bool(key: &str, default: bool) -> bool {
+        Self::get(key, if default { "true" } else { "false" }, Some("bool"))
+            .parse()
+            .unwrap_or(default)
+    }
+
+    pub fn get_path(key: &str, default: &str) -> String {
+        Self::get(key, default, Some("path"))
+    }
+
+    fn get(key: &str, default: &str, value_type: Option<&str>) -> String {
+        let mut args = vec!["config", "--get", "--default", default];
+        if let Some(value_type) = value_type {
+            args.push("--type");
+            args.push(value_type);
+        }
+        args.push(key);
+
+        let output = Command::new("git").args(&args).output();
+        match output {
+            Ok(out) if out.status.success() => {
+                String::from_utf8_lossy(&out.stdout).trim().to_string()
+            }
+            _ => default.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GitConfig;
+    use std::process::Command;
+
+    // An `oth.*` key that's never set, so these fall through to `default`.
+    const UNSET_KEY: &str = "oth.doesNotExistForTesting";
+
+    #[test]
+    fn test_get_string_falls_back_to_default() {
+        assert_eq!(GitConfig::get_string(UNSET_KEY, "fallback"), "fallback");
+    }
+
+    #[test]
+    fn test_get_string_reads_configured_value() {
+        let key = "oth.configReadbackTest";
+        Command::new("git")
+            .args(&["config", "--local", key, "configured-value"])
+            .status()
+            .expect("error setting test config");
+
+        let result = GitConfig::get_string(key, "fallback");
+
+        Command::new("git")
+            .args(&["config", "--local", "--unset", key])
+            .status()
+            .expect("error unsetting test config");
+
+        assert_eq!(result, "configured-value");
+    }
+
+    #[test]
+    fn test_get_bool_falls_back_to_default() {
+        assert!(GitConfig::get_bool(UNSET_KEY, true));
+        assert!(!GitConfig::get_bool(UNSET_KEY, false));
+    }
+
+    #[test]
+    fn test_get_path_falls_back_to_default() {
+        assert_eq!(GitConfig::get_path(UNSET_KEY, "/tmp/fallback"), "/tmp/fallback");
+    }
+}
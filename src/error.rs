@@ -0,0 +1,33 @@
+use std::fmt;
+
+/// Structured error type for operations that used to panic via `expect`.
+#[derive(Debug)]
+pub enum OthError {
+    Git2(git2::Error),
+    Io(std::io::Error),
+    Message(String),
+}
+
+impl fmt::Display for OthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OthError::Git2(e) => write!(f, "git error: {}", e),
+            OthError::Io(e) => write!(f, "io error: {}", e),
+            OthError::Message(m) => write!(f, "{}", m),
+        }
+    }
+}
+
+impl std::error::Error for OthError {}
+
+impl From<git2::Error> for OthError {
+    fn from(e: git2::Error) -> Self {
+        OthError::Git2(e)
+    }
+}
+
+impl From<std::io::Error> for OthError {
+    fn from(e: std::io::Error) -> Self {
+        OthError::Io(e)
+    }
+}
@@ -0,0 +1,84 @@
+use std::io::{Cursor, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use skim::prelude::*;
+
+/// Interchangeable fuzzy-selector backend, so users can swap the bundled
+/// skim defaults for their own tuned `fzf`/`sk` config and keybindings.
+pub trait Finder {
+    fn select(&self, items: &[String], preview_cmd: &str) -> Vec<String>;
+}
+
+pub struct SkimFinder;
+
+impl Finder for SkimFinder {
+    fn select(&self, items: &[String], preview_cmd: &str) -> Vec<String> {
+        let options = SkimOptionsBuilder::default()
+            .multi(true)
+            .preview(Some(preview_cmd.to_string()))
+            .build()
+            .unwrap();
+
+        let item_reader = SkimItemReader::default();
+        let skim_items = item_reader.of_bufread(Cursor::new(items.join("\n")));
+        let Some(out) = Skim::run_with(options, Some(skim_items)) else {
+            return Vec::new();
+        };
+        if out.is_abort {
+            return Vec::new();
+        }
+
+        out.selected_items
+            .iter()
+            .map(|item| item.output().to_string())
+            .collect()
+    }
+}
+
+/// Pipes the newline-joined item list into an external finder binary
+/// (`fzf`, `sk`, ...) over stdin and reads the selected lines back from
+/// stdout.
+pub struct ExternalFinder {
+    binary: String,
+}
+
+impl ExternalFinder {
+    pub fn new(binary: String) -> Self {
+        Self { binary }
+    }
+}
+
+impl Finder for ExternalFinder {
+    fn select(&self, items: &[String], preview_cmd: &str) -> Vec<String> {
+        let mut child = Command::new(&self.binary)
+            .arg("--multi")
+            .arg(format!("--preview={}", preview_cmd))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect(format!("error running finder {}", self.binary).as_str());
+
+        // Write on a separate thread: if the item list is larger than the
+        // OS pipe buffer, the finder can start draining stdout (emitting
+        // preview output, etc.) before we've finished writing stdin, and
+        // writing and `wait_with_output` on the same thread would deadlock.
+        let mut stdin = child.stdin.take().expect("error opening finder stdin");
+        let items = items.join("\n");
+        let writer = thread::spawn(move || {
+            stdin
+                .write_all(items.as_bytes())
+                .expect("error writing to finder stdin");
+        });
+
+        let output = child
+            .wait_with_output()
+            .expect("error reading finder output");
+        writer.join().expect("finder stdin writer thread panicked");
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect()
+    }
+}
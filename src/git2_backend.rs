@@ -0,0 +1,183 @@
+use std::path::Path;
+
+use git2::Repository;
+
+use crate::error::OthError;
+
+/// In-process libgit2 counterpart to the shell-out helpers in `backend`.
+///
+/// Avoids spawning a `git` subprocess per call and reads packed refs
+/// correctly (unlike poking at `.git/refs/...` directly).
+pub struct Git2Backend {
+    repo: Repository,
+}
+
+impl Git2Backend {
+    pub fn open(workdir: &Path) -> Result<Self, OthError> {
+        let repo = Repository::discover(workdir)?;
+        Ok(Self { repo })
+    }
+
+    /// Repository toplevel directory, replacing `git rev-parse --show-toplevel`.
+    pub fn workdir(&self) -> Result<String, OthError> {
+        self.repo
+            .workdir()
+            .and_then(|p| p.to_str())
+            .map(String::from)
+            .ok_or_else(|| OthError::Message("repository has no working directory (bare repo?)".to_string()))
+    }
+
+    /// Current branch name, replacing `git branch --show-current`.
+    pub fn current_branch(&self) -> Result<String, OthError> {
+        self.repo
+            .head()?
+            .shorthand()
+            .map(String::from)
+            .ok_or_else(|| OthError::Message("HEAD has no shorthand".to_string()))
+    }
+
+    /// Resolve the upstream remote of the current branch via its configured
+    /// tracking ref, rather than parsing `rev-parse --symbolic-full-name`.
+    pub fn upstream_remote(&self) -> Result<String, OthError> {
+        let head = self.repo.head()?;
+        let branch_name = head
+            .shorthand()
+            .ok_or_else(|| OthError::Message("HEAD has no shorthand".to_string()))?;
+        let branch = self
+            .repo
+            .find_branch(branch_name, git2::BranchType::Local)?;
+        let upstream = branch.upstream()?;
+        let upstream_ref = upstream
+            .name()?
+            .ok_or_else(|| OthError::Message("upstream branch has no name".to_string()))?;
+        upstream_ref
+            .strip_prefix("refs/remotes/")
+            .and_then(|rest| rest.split('/').next())
+            .map(String::from)
+            .ok_or_else(|| OthError::Message(format!("unexpected upstream ref {}", upstream_ref)))
+    }
+
+    /// Equivalent of the old `fs::read_to_string(".git/refs/remotes/<remote>/HEAD")`,
+    /// but goes through libgit2 so it also works when refs are packed.
+    pub fn default_branch(&self, remote: &str) -> Result<String, OthError> {
+        let reference = self
+            .repo
+            .find_reference(&format!("refs/remotes/{}/HEAD", remote))?;
+        let target = reference
+            .symbolic_target()
+            .ok_or_else(|| OthError::Message(format!("{}/HEAD is not symbolic", remote)))?;
+        target
+            .strip_prefix(&format!("refs/remotes/{}/", remote))
+            .map(String::from)
+            .ok_or_else(|| OthError::Message(format!("unexpected symbolic target {}", target)))
+    }
+
+    pub fn is_staged(&self) -> Result<bool, OthError> {
+        let head_tree = self.repo.head()?.peel_to_tree()?;
+        let diff = self.repo.diff_tree_to_index(Some(&head_tree), None, None)?;
+        Ok(diff.deltas().len() > 0)
+    }
+
+    /// Confirms `range` (e.g. `v1.2.0..HEAD`) resolves to real commits,
+    /// mirroring `git rev-parse <range>`.
+    pub fn resolve_range(&self, range: &str) -> Result<(), OthError> {
+        self.repo.revparse(range)?;
+        Ok(())
+    }
+
+    /// Ahead/behind count of HEAD relative to `other`, replacing
+    /// `rev-list --count HEAD ^<other>`.
+    pub fn ahead_behind(&self, other: &str) -> Result<(usize, usize), OthError> {
+        let local = self.repo.head()?.peel_to_commit()?.id();
+        let other_id = self.repo.revparse_single(other)?.peel_to_commit()?.id();
+        Ok(self.repo.graph_ahead_behind(local, other_id)?)
+    }
+
+    /// Resolves `spec` (a single rev) to the tree it points at.
+    fn resolve_tree<'a>(&'a self, spec: &str) -> Result<git2::Tree<'a>, OthError> {
+        Ok(self.repo.revparse_single(spec)?.peel_to_tree()?)
+    }
+
+    /// Builds the diff for `diff_spec` against the working tree/index, or,
+    /// for a range, the diff `git diff <diff_spec>` would produce: a
+    /// two-dot range (`v1.2.0..HEAD`) diffs the two sides directly, while a
+    /// three-dot range (`v1.2.0...HEAD`) diffs their merge-base against the
+    /// right-hand side.
+    fn diff_for_spec(&self, diff_spec: &str, staged: bool) -> Result<git2::Diff, OthError> {
+        // Three-dot must be checked before two-dot, since "..." also
+        // contains "..".
+        if let Some((from, to)) = diff_spec.split_once("...") {
+            let from_commit = self.repo.revparse_single(from)?.peel_to_commit()?;
+            let to_commit = self.repo.revparse_single(to)?.peel_to_commit()?;
+            let merge_base = self.repo.merge_base(from_commit.id(), to_commit.id())?;
+            let merge_base_tree = self.repo.find_commit(merge_base)?.tree()?;
+            let to_tree = to_commit.tree()?;
+            return Ok(self
+                .repo
+                .diff_tree_to_tree(Some(&merge_base_tree), Some(&to_tree), None)?);
+        }
+
+        if let Some((from, to)) = diff_spec.split_once("..") {
+            let from_tree = self.resolve_tree(from)?;
+            let to_tree = self.resolve_tree(to)?;
+            return Ok(self
+                .repo
+                .diff_tree_to_tree(Some(&from_tree), Some(&to_tree), None)?);
+        }
+
+        let tree = self.resolve_tree(diff_spec)?;
+        if staged {
+            Ok(self.repo.diff_tree_to_index(Some(&tree), None, None)?)
+        } else {
+            Ok(self.repo.diff_tree_to_workdir_with_index(Some(&tree), None)?)
+        }
+    }
+
+    /// Changed file paths for `diff_spec`, using libgit2's diff objects
+    /// instead of `git diff --name-only`.
+    pub fn changed_files(&self, diff_spec: &str, staged: bool) -> Result<Vec<String>, OthError> {
+        let diff = self.diff_for_spec(diff_spec, staged)?;
+
+        let mut files = Vec::new();
+        diff.foreach(
+            &mut |delta, _progress| {
+                if let Some(path) = delta.new_file().path().and_then(|p| p.to_str()) {
+                    files.push(path.to_string());
+                }
+                true
+            },
+            None,
+            None,
+            None,
+        )?;
+        Ok(files)
+    }
+
+    /// Unified diff text for a single file in `diff_spec`, reconstructed
+    /// from libgit2's per-line diff callback instead of shelling out to
+    /// `git diff -- <file>`.
+    pub fn file_diff(&self, diff_spec: &str, staged: bool, file: &str) -> Result<String, OthError> {
+        let diff = self.diff_for_spec(diff_spec, staged)?;
+
+        let mut patch_text = String::new();
+        diff.print(git2::DiffFormat::Patch, |delta, _hunk, line| {
+            let is_target = delta
+                .new_file()
+                .path()
+                .and_then(|p| p.to_str())
+                .map(|p| p == file)
+                .unwrap_or(false);
+            if !is_target {
+                return true;
+            }
+            if matches!(line.origin(), ' ' | '+' | '-') {
+                patch_text.push(line.origin());
+            }
+            if let Ok(content) = std::str::from_utf8(line.content()) {
+                patch_text.push_str(content);
+            }
+            true
+        })?;
+        Ok(patch_text)
+    }
+}
@@ -1,26 +1,21 @@
 use std::env;
-use std::fs;
-use std::io::Cursor;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use clap::{Parser, ValueEnum};
-use regex::Regex;
-use shlex;
-use skim::prelude::*;
+
+mod backend;
+mod config;
+mod error;
+mod finder;
+mod git2_backend;
+
+use backend::{detect_vcs, Backend, DiffMode, Engine, GitBackend, HgBackend, JujutsuBackend, VcsKind};
+use config::GitConfig;
+use finder::{ExternalFinder, Finder, SkimFinder};
 
 const DEFAULT_EDITOR: &str = "nvim";
 const RELATIVE_REFERENCE: &str = "../";
-const REMOTE_FALLBACK: &str = "origin";
-
-#[derive(Debug, Clone, PartialEq, Eq, ValueEnum)]
-enum DiffMode {
-    Branch,
-    Remote,
-    Revlist,
-    RevlistRemote,
-    Upstream,
-}
 
 #[derive(Parser, Debug)]
 #[command(
@@ -29,26 +24,85 @@ enum DiffMode {
   long_about = None
 )]
 struct Args {
-    #[arg(short, long, value_enum, default_value = "revlist-remote")]
-    diff_mode: DiffMode,
+    #[arg(short, long, value_enum)]
+    diff_mode: Option<DiffMode>,
     #[arg(short, long)]
     editor: Option<String>,
     #[arg(long)]
     remote_override: Option<String>,
-    #[arg(short, long)]
+    #[arg(short, long, conflicts_with = "no_selector")]
     selector: bool,
+    /// Disable the interactive selector, overriding `oth.selector` from git config.
+    #[arg(long, conflicts_with = "selector")]
+    no_selector: bool,
+    #[arg(long, value_enum, default_value = "shell")]
+    engine: Engine,
+    /// "skim" for the embedded selector, or a finder binary on PATH (e.g. "fzf", "sk").
+    #[arg(long, env = "OTH_FINDER", default_value = "skim")]
+    finder: String,
+    /// Jump the editor to the first changed hunk's line instead of the top of the file.
+    #[arg(long, conflicts_with = "no_line_jump")]
+    line_jump: bool,
+    /// Disable editor line-jump, overriding `oth.lineJump` from git config.
+    #[arg(long, conflicts_with = "line_jump")]
+    no_line_jump: bool,
+    /// Commit range to diff directly (e.g. `v1.2.0..HEAD`), used with `--diff-mode range`.
+    #[arg(long)]
+    range: Option<String>,
 }
 
-fn get_default_branch(remote: &String, workdir_path: &Path) -> Option<String> {
-    let remote_head = workdir_path.join(format!(".git/refs/remotes/{}/HEAD", remote));
-    let content = fs::read_to_string(remote_head.clone())
-        .expect(format!("Could not read remote HEAD {}", remote_head.display()).as_str());
-    let ref_line = content.trim();
-    let regex = Regex::new(format!("ref: refs/remotes/{}/(.*)", remote).as_str()).unwrap();
-    if let Some(captures) = regex.captures(ref_line) {
-        return Some(captures[1].parse().unwrap());
+/// Built-in `{file}`/`{line}` jump templates for editors with a known
+/// "open at line" syntax; anything else falls back to just the file path.
+const EDITOR_TEMPLATES: &[(&str, &str)] = &[
+    ("vim", "+{line} {file}"),
+    ("nvim", "+{line} {file}"),
+    ("emacs", "+{line} {file}"),
+    ("code", "-g {file}:{line}"),
+    ("code-insiders", "-g {file}:{line}"),
+    ("codium", "-g {file}:{line}"),
+    ("subl", "{file}:{line}"),
+];
+
+/// Builds the argument list for invoking `editor` on `file`, jumping to
+/// `line` via an `oth.editorTemplate` override or, failing that, a built-in
+/// template keyed by the editor's basename.
+fn editor_args(editor: &str, file: &str, line: Option<u32>) -> Vec<String> {
+    let Some(line) = line else {
+        return vec![file.to_string()];
+    };
+
+    let configured = GitConfig::get_string("oth.editorTemplate", "");
+    let template = if !configured.is_empty() {
+        Some(configured)
+    } else {
+        let editor_name = Path::new(editor)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or(editor);
+        EDITOR_TEMPLATES
+            .iter()
+            .find(|(name, _)| *name == editor_name)
+            .map(|(_, template)| template.to_string())
+    };
+
+    match template {
+        // Split into tokens first, then substitute each token whole, so a
+        // `{file}` value containing spaces doesn't get sliced into extra args.
+        Some(template) => template
+            .split_whitespace()
+            .map(|token| token.replace("{file}", file).replace("{line}", &line.to_string()))
+            .collect(),
+        None => vec![file.to_string()],
     }
-    None
+}
+
+/// Finds the new-file line number of the first hunk in a unified diff
+/// (`@@ -a,b +c,d @@`), so the editor can land on the first change instead
+/// of the top of the file.
+fn first_hunk_line(diff: &str) -> Option<u32> {
+    let header = diff.lines().find(|line| line.starts_with("@@ "))?;
+    let new_range = header.split(' ').nth(2)?;
+    new_range.strip_prefix('+')?.split(',').next()?.parse().ok()
 }
 
 fn get_editor(editor: Option<String>) -> String {
@@ -60,52 +114,52 @@ fn get_editor(editor: Option<String>) -> String {
         return editor.into_string().unwrap();
     }
 
+    let configured = GitConfig::get_string("oth.editor", "");
+    if !configured.is_empty() {
+        return configured;
+    }
+
     DEFAULT_EDITOR.to_string()
 }
 
-fn git_output(args: Vec<&str>) -> Result<String, String> {
-    let cmd = args.join(" ");
-    let output = Command::new("git")
-        .args(args)
-        .output()
-        .expect(format!("error running command {}", cmd).as_str());
-    if !output.status.success() {
-        return Err(String::from_utf8(output.stderr).unwrap());
+fn resolve_diff_mode(diff_mode: Option<DiffMode>) -> DiffMode {
+    if let Some(diff_mode) = diff_mode {
+        return diff_mode;
     }
-    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
-    Ok(stdout)
+
+    let configured = GitConfig::get_string("oth.diffMode", "revlist-remote");
+    DiffMode::from_str(&configured, true).unwrap_or(DiffMode::RevlistRemote)
 }
 
-fn get_remote() -> String {
-    let full_name = git_output(vec![
-        "rev-parse",
-        "--abbrev-ref",
-        "--symbolic-full-name",
-        "@{u}",
-    ]);
-    if full_name.is_err() {
-        return REMOTE_FALLBACK.to_string();
+/// Resolves a CLI on/off flag pair against a config fallback, so an explicit
+/// `--foo`/`--no-foo` always overrides `config_key` instead of only ever
+/// pushing it towards `true`.
+fn resolve_bool_flag(on: bool, off: bool, config_key: &str, default: bool) -> bool {
+    if off {
+        false
+    } else if on {
+        true
+    } else {
+        GitConfig::get_bool(config_key, default)
     }
-    full_name
-        .expect("error getting remote")
-        .split('/')
-        .nth(0)
-        .unwrap()
-        .to_string()
 }
 
-fn is_staged() -> bool {
-    !Command::new("git")
-        .args(&["diff", "--cached", "--shortstat"])
-        .output()
-        .expect("error running git")
-        .stdout
-        .is_empty()
+/// Walks up from `start` looking for the VCS marker directory (`.jj`/`.hg`),
+/// used when the git-specific `rev-parse --show-toplevel` doesn't apply.
+fn find_root(start: &Path, marker: &str) -> PathBuf {
+    let mut dir = Some(start);
+    while let Some(current) = dir {
+        if current.join(marker).exists() {
+            return current.to_path_buf();
+        }
+        dir = current.parent();
+    }
+    start.to_path_buf()
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::relativize;
+    use crate::{editor_args, first_hunk_line, relativize, resolve_bool_flag};
 
     // use super::*;
     #[test]
@@ -113,6 +167,46 @@ mod tests {
         assert_eq!(relativize("foo/bar/baz", "foo/hey"), "../../hey");
         assert_eq!(relativize("foo/bar/baz", "readme.md"), "../../../readme.md");
     }
+
+    #[test]
+    fn test_first_hunk_line() {
+        let diff = "diff --git a/foo b/foo\nindex 123..456 100644\n--- a/foo\n+++ b/foo\n@@ -1,2 +3,4 @@\n context\n+added\n";
+        assert_eq!(first_hunk_line(diff), Some(3));
+        assert_eq!(first_hunk_line(""), None);
+    }
+
+    #[test]
+    fn test_resolve_bool_flag_cli_overrides_config() {
+        // --no-foo always wins, even over --foo.
+        assert!(!resolve_bool_flag(true, true, "oth.doesNotExist", true));
+        // --foo wins without needing to consult config.
+        assert!(resolve_bool_flag(true, false, "oth.doesNotExist", false));
+    }
+
+    #[test]
+    fn test_editor_args() {
+        assert_eq!(editor_args("nvim", "foo.rs", None), vec!["foo.rs"]);
+        assert_eq!(
+            editor_args("nvim", "foo.rs", Some(5)),
+            vec!["+5", "foo.rs"]
+        );
+        assert_eq!(
+            editor_args("/usr/bin/code", "foo.rs", Some(5)),
+            vec!["-g", "foo.rs:5"]
+        );
+    }
+
+    #[test]
+    fn test_editor_args_file_with_spaces() {
+        assert_eq!(
+            editor_args("nvim", "src/my notes.rs", Some(3)),
+            vec!["+3", "src/my notes.rs"]
+        );
+        assert_eq!(
+            editor_args("/usr/bin/code", "src/my notes.rs", Some(3)),
+            vec!["-g", "src/my notes.rs:3"]
+        );
+    }
 }
 
 fn relativize(from: &str, to: &str) -> String {
@@ -160,109 +254,98 @@ fn relativize(from: &str, to: &str) -> String {
 
 fn main() {
     let args = Args::parse();
-    let workdir = git_output(vec!["rev-parse", "--show-toplevel"]).expect("error getting workdir");
-    let remote = if args.remote_override.is_some() {
-        args.remote_override.unwrap()
+    let cwd = env::current_dir().expect("error getting current directory");
+
+    let configured_remote = GitConfig::get_string("oth.remote", "");
+    let configured_remote = if configured_remote.is_empty() {
+        None
     } else {
-        get_remote()
+        Some(configured_remote)
     };
-    let default_branch_name = get_default_branch(&remote, workdir.as_ref()).unwrap();
-    let staged_changes = is_staged();
 
-    let mut diff_cmd = match args.diff_mode {
-        DiffMode::Branch => {
-            format!("diff {}", default_branch_name)
-        }
-        DiffMode::Upstream => {
-            let branch =
-                git_output(vec!["branch", "--show-current"]).expect("error getting branch");
-            format!("diff {}/{}", remote, branch)
+    let (workdir, backend): (PathBuf, Box<dyn Backend>) = match detect_vcs(&cwd) {
+        VcsKind::Jujutsu => {
+            let root = find_root(&cwd, ".jj");
+            (root.clone(), Box::new(JujutsuBackend::new(root, args.range.clone())))
         }
-        DiffMode::Remote => {
-            format!("diff {}/{}", remote, default_branch_name)
+        VcsKind::Mercurial => {
+            let root = find_root(&cwd, ".hg");
+            (root.clone(), Box::new(HgBackend::new(root, args.range.clone())))
         }
-        DiffMode::Revlist => {
-            let rev_list_count = git_output(vec![
-                "rev-list",
-                "--count",
-                "HEAD",
-                format!("^{}", default_branch_name).as_str(),
-            ])
-            .expect("error getting rev list");
-            format!("diff HEAD~{}", rev_list_count)
-        }
-        DiffMode::RevlistRemote => {
-            let rev_list_count = git_output(vec![
-                "rev-list",
-                "--count",
-                "HEAD",
-                format!("^{}", default_branch_name).as_str(),
-            ])
-            .expect("error getting rev list");
-            format!("diff {}/HEAD~{}", remote, rev_list_count)
+        VcsKind::Git => {
+            let backend = GitBackend::new(
+                &cwd,
+                args.remote_override.clone(),
+                configured_remote,
+                args.engine.clone(),
+                args.range.clone(),
+            )
+            .expect("error opening repository");
+            let workdir = PathBuf::from(backend.workdir());
+            (workdir, Box::new(backend))
         }
     };
-    if staged_changes {
-        diff_cmd = format!("{} --cached", diff_cmd);
-    }
-    let files_cmd = format!("{} --name-only", diff_cmd);
-
-    let cmd_vec = shlex::split(files_cmd.as_str()).expect("error parsing command string");
-    let git_arg = cmd_vec.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
-    let file_names = git_output(git_arg)
-        .expect("error getting file names")
-        .split('\n')
-        .filter(|s| !s.is_empty())
-        .map(String::from)
-        .collect::<Vec<String>>();
+
+    let staged_changes = backend.is_staged().expect("error checking staged changes");
+    let diff_mode = resolve_diff_mode(args.diff_mode);
+    let diff_spec = backend
+        .diff_spec(&diff_mode)
+        .expect("error building diff spec");
+
+    let file_names = backend
+        .changed_files(&diff_spec, staged_changes)
+        .expect("error getting changed files");
 
     if file_names.is_empty() {
         return;
     }
 
-    let cwd = env::current_dir().unwrap();
+    let workdir_str = workdir.to_str().expect("error getting workdir");
     let current_dir = cwd
         .to_str()
         .expect("error getting current directory")
-        .strip_prefix(workdir.as_str())
+        .strip_prefix(workdir_str)
         .expect("error striping prefix");
     let file_names = file_names
         .iter()
         .map(|f| {
-            let abs_path = Path::new(workdir.as_str()).join(f);
+            let abs_path = workdir.join(f);
             let file_path = abs_path
-                .strip_prefix(workdir.as_str())
+                .strip_prefix(workdir_str)
                 .expect("error striping prefix");
             relativize(current_dir, file_path.to_str().expect("error getting path"))
         })
         .collect::<Vec<String>>();
 
-    if !args.selector {
+    let selector = resolve_bool_flag(args.selector, args.no_selector, "oth.selector", false);
+    if !selector {
         file_names.iter().for_each(|s| {
             println!("{}", s);
         });
         return;
     }
 
-    let preview = format!("git {} --color=always -- {{}}", diff_cmd);
-    let options = SkimOptionsBuilder::default()
-        .multi(true)
-        .preview(Some(preview))
-        .build()
-        .unwrap();
-
-    let item_reader = SkimItemReader::default();
-    let items = item_reader.of_bufread(Cursor::new(file_names.join("\n")));
-    let skim_out = Skim::run_with(options, Some(items)).unwrap();
-
-    if skim_out.is_abort {
-        return;
-    }
+    let preview = backend.preview_command(&diff_spec, staged_changes);
+    let finder: Box<dyn Finder> = if args.finder == "skim" {
+        Box::new(SkimFinder)
+    } else {
+        Box::new(ExternalFinder::new(args.finder))
+    };
+    let selected = finder.select(&file_names, &preview);
 
     let editor = get_editor(args.editor);
-    for item in skim_out.selected_items {
+    let line_jump = resolve_bool_flag(args.line_jump, args.no_line_jump, "oth.lineJump", false);
+    for item in selected {
+        let line = if line_jump {
+            backend
+                .file_diff(&diff_spec, staged_changes, &item)
+                .ok()
+                .and_then(|diff| first_hunk_line(&diff))
+        } else {
+            None
+        };
         Command::new(editor.clone())
-            .arg(item.output().as_ref())
+            .args(editor_args(&editor, &item, line))
             .status()
             .unwrap();
     }